@@ -1,19 +1,66 @@
 use cargo_export::target_file_name;
+use cargo_metadata::{Message, PackageId};
 use getopts::{Fail, Options};
-use serde::Deserialize;
-use serde_json::Value;
+use serde::{Deserialize, Serialize};
 use std::{
     collections::VecDeque,
     fs,
-    io::{BufRead, BufReader},
-    path::PathBuf,
+    io::BufReader,
+    path::{Path, PathBuf},
     process::{exit, Command, Stdio},
 };
 
-#[derive(Deserialize, Debug)]
-struct CompilerArtifact {
-    reason: String,
-    executable: String,
+/// A single exported file together with the metadata needed to map it back to
+/// the cargo target that produced it. Serialized into the `--index` file so
+/// downstream runners can resolve "the `foo` bench" to a concrete path without
+/// re-deriving the mangled file name.
+#[derive(Serialize, Debug)]
+struct ExportedArtifact {
+    package: String,
+    target: String,
+    kind: String,
+    original_path: PathBuf,
+    exported_path: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<String>,
+}
+
+/// Defaults read from the `[package.metadata.export]` table of the workspace
+/// manifest. Every field is optional and is only consulted when the matching
+/// command-line flag is absent — the CLI always wins, mirroring how cargo
+/// layers configuration under explicit arguments.
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+struct ExportConfig {
+    directory: Option<String>,
+    tag: Option<String>,
+    kind: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    package: Option<ManifestPackage>,
+}
+
+#[derive(Deserialize)]
+struct ManifestPackage {
+    metadata: Option<ManifestMetadata>,
+}
+
+#[derive(Deserialize)]
+struct ManifestMetadata {
+    export: Option<ExportConfig>,
+}
+
+/// An exportable file discovered in cargo's artifact stream, before the
+/// destination name has been resolved.
+struct PendingArtifact {
+    package: String,
+    target: String,
+    kind: String,
+    triple: Option<String>,
+    profile: Option<String>,
+    from: PathBuf,
 }
 
 fn main() {
@@ -49,7 +96,16 @@ fn main() {
         print_version_and_exit();
     }
 
-    let Some(target) = matches.free.first() else {
+    let config = load_export_config();
+
+    // PATH may come from the command line or, failing that, from the
+    // `directory` default in `[package.metadata.export]`.
+    let Some(target) = matches
+        .free
+        .first()
+        .map(String::as_str)
+        .or(config.directory.as_deref())
+    else {
         print_usage_and_exit(&opts, Some(Fail::OptionMissing("PATH".to_string())));
     };
 
@@ -67,9 +123,27 @@ fn main() {
         cargo_args.insert(1, "--message-format=json");
     }
 
-    let tag_name = matches.opt_str("tag");
+    let tag_name = matches.opt_str("tag").or_else(|| config.tag.clone());
+
+    // Resolved kind filter: explicit `--kind` flags, else the manifest
+    // default, else `None` — which exports every executable and recognised
+    // library output.
+    let kind_filter = {
+        let cli = matches.opt_strs("kind");
+        if !cli.is_empty() {
+            Some(cli)
+        } else {
+            config.kind.clone()
+        }
+    };
+
     let dry_run = matches.opt_present("dry-run");
     let verbose = matches.opt_present("verbose") || dry_run;
+    let split_by_target = matches.opt_present("split-by-target");
+
+    // Triples the user asked cargo to build for; used to recognise the
+    // `target/<triple>/` path segment of each produced artifact.
+    let triples = target_triples(&cargo_args);
 
     let mut command = Command::new("cargo")
         .args(cargo_args)
@@ -78,21 +152,72 @@ fn main() {
         .expect("Unable to spawn cargo process");
     let stdout = command.stdout.take().unwrap();
     let stdout = BufReader::new(stdout);
+
+    // A single `compiler-artifact` message can produce several files: an
+    // executable and/or one or more library outputs. We pick them up from two
+    // independent sources rather than keying off `target.kind` alone, which
+    // would drop a library crate's own unit-test binary (kind `["lib"]`).
     let mut artifacts = Vec::new();
-    for line in stdout.lines() {
-        let line = line.unwrap();
-        let Ok(value) = serde_json::from_str::<Value>(&line) else {
-            if verbose {
-                eprintln!("cargo output: {}", line);
+    for message in Message::parse_stream(stdout) {
+        match message {
+            Ok(Message::CompilerArtifact(artifact)) => {
+                let package = package_name(&artifact.package_id);
+                let mut push = |kind: &str, from: PathBuf| {
+                    let triple = triple_for(&from, &triples);
+                    let profile = profile_for(&from);
+                    artifacts.push(PendingArtifact {
+                        package: package.clone(),
+                        target: artifact.target.name.clone(),
+                        kind: kind.to_string(),
+                        triple,
+                        profile,
+                        from,
+                    });
+                };
+
+                // An `executable` covers bin/test/bench/example targets and a
+                // library crate's own unit-test binary; the presence of the
+                // field — not the crate kind — is what makes it exportable.
+                if let Some(executable) = &artifact.executable {
+                    let kind = exe_kind(&artifact.target.kind);
+                    if kind_selected(&kind_filter, &kind) {
+                        push(&kind, PathBuf::from(executable.as_str()));
+                    }
+                }
+
+                // Library outputs (`cdylib`/`staticlib`/`dylib`) are listed in
+                // `filenames`; match them to the crate kind by extension so the
+                // intermediate `.rlib`/`.rmeta` of a multi-output target are not
+                // copied or mislabelled.
+                for kind in &artifact.target.kind {
+                    if !is_library_export_kind(kind) || !kind_selected(&kind_filter, kind) {
+                        continue;
+                    }
+                    for filename in &artifact.filenames {
+                        if extension_matches_kind(Path::new(filename.as_str()), kind) {
+                            push(kind, PathBuf::from(filename.as_str()));
+                        }
+                    }
+                }
             }
-            panic!("Unable to parse json from cargo");
-        };
-        let message = serde_json::from_value::<CompilerArtifact>(value)
-            .ok()
-            .filter(|m| m.reason == "compiler-artifact");
-        if let Some(message) = message {
-            artifacts.push(message);
-        };
+            // Forward the compiler's own warnings and errors verbatim, exactly
+            // as a bare `cargo build` would print them.
+            Ok(Message::CompilerMessage(msg)) => {
+                if let Some(rendered) = msg.message.rendered {
+                    eprint!("{}", rendered);
+                }
+            }
+            // Non-JSON lines are passed through rather than treated as fatal,
+            // so diagnostics are never swallowed. Other message kinds
+            // (build-script and build-finished records) are ignored.
+            Ok(Message::TextLine(line)) => {
+                println!("{}", line);
+            }
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("[cargo-export] error reading cargo output: {}", err);
+            }
+        }
     }
     let exit_code = command.wait().expect("Failed executing cargo");
     if !exit_code.success() {
@@ -106,11 +231,25 @@ fn main() {
     }
 
     // Copying artifacts
-    for artfact in artifacts {
-        let from = PathBuf::from(&artfact.executable);
+    let mut index = Vec::with_capacity(artifacts.len());
+    for artifact in artifacts {
+        let from = artifact.from;
         let file_name = from.file_name().and_then(|n| n.to_str()).unwrap();
-        let file_name = target_file_name(file_name, tag_name.as_deref());
-        let to = target_dir.join(&file_name);
+        // Expand `{pkg}`/`{target}`/`{triple}`/`{profile}`/`{date}` in the tag
+        // for this specific artifact before it is folded into the file name.
+        let resolved_tag = tag_name.as_deref().map(|t| expand_tag(t, &artifact));
+        let file_name = target_file_name(file_name, resolved_tag.as_deref());
+
+        // When splitting by target, drop each artifact into a `<triple>/`
+        // subdirectory so cross-compiled outputs don't overwrite each other.
+        let dest_dir = match (split_by_target, &artifact.triple) {
+            (true, Some(triple)) => target_dir.join(triple),
+            _ => target_dir.clone(),
+        };
+        if !dry_run && !dest_dir.exists() {
+            fs::create_dir_all(&dest_dir).unwrap();
+        }
+        let to = dest_dir.join(&file_name);
 
         if verbose {
             eprintln!(
@@ -121,9 +260,209 @@ fn main() {
             );
         }
         if !dry_run {
-            fs::copy(from, to).expect("Unable to copy file");
+            fs::copy(&from, &to).expect("Unable to copy file");
+        }
+
+        index.push(ExportedArtifact {
+            package: artifact.package,
+            target: artifact.target,
+            kind: artifact.kind,
+            original_path: from,
+            exported_path: to,
+            tag: resolved_tag,
+        });
+    }
+
+    if let Some(index_path) = matches.opt_str("index") {
+        let json = serde_json::to_string_pretty(&index).expect("Unable to serialize index");
+        if verbose {
+            eprintln!(
+                "[cargo-export] writing index of {} artifact(s) to '{}'{}",
+                index.len(),
+                index_path,
+                if dry_run { " (dry run)" } else { "" }
+            );
+        }
+        if !dry_run {
+            fs::write(&index_path, json).expect("Unable to write index file");
+        }
+    }
+}
+
+/// Extracts the package name from a cargo [`PackageId`], handling both the
+/// legacy `"name version (source)"` spelling and the newer
+/// `"source#name@version"` / `"source#version"` package-id spec.
+fn package_name(id: &PackageId) -> String {
+    let repr = &id.repr;
+
+    // legacy format: "name version (source)"
+    if let Some(name) = repr.split_whitespace().next() {
+        if !name.contains('+') && !name.contains('/') {
+            return name.to_string();
+        }
+    }
+
+    // modern format: the fragment after '#' is either "name@version" or, for
+    // path/git packages, just "version" (name is the last path segment).
+    let (source, fragment) = repr.rsplit_once('#').unwrap_or(("", repr));
+    if let Some((name, _)) = fragment.split_once('@') {
+        return name.to_string();
+    }
+    source
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or(fragment)
+        .to_string()
+}
+
+/// Locates the manifest governing the current directory, preferring cargo's
+/// own resolution (`cargo locate-project`) and falling back to walking up from
+/// the current directory when cargo is unavailable.
+fn locate_manifest() -> Option<PathBuf> {
+    if let Ok(output) = Command::new("cargo")
+        .args(["locate-project", "--message-format=plain"])
+        .output()
+    {
+        if output.status.success() {
+            if let Ok(path) = String::from_utf8(output.stdout) {
+                let path = path.trim();
+                if !path.is_empty() {
+                    return Some(PathBuf::from(path));
+                }
+            }
+        }
+    }
+
+    let mut dir = std::env::current_dir().ok()?;
+    loop {
+        let candidate = dir.join("Cargo.toml");
+        if candidate.exists() {
+            return Some(candidate);
+        }
+        if !dir.pop() {
+            return None;
+        }
+    }
+}
+
+/// Reads `[package.metadata.export]` from the workspace manifest. Any problem
+/// locating, reading, or parsing the manifest yields the empty defaults — a
+/// missing or malformed table should never stop an otherwise valid export.
+fn load_export_config() -> ExportConfig {
+    let Some(path) = locate_manifest() else {
+        return ExportConfig::default();
+    };
+    let Ok(contents) = fs::read_to_string(path) else {
+        return ExportConfig::default();
+    };
+    toml::from_str::<Manifest>(&contents)
+        .ok()
+        .and_then(|m| m.package)
+        .and_then(|p| p.metadata)
+        .and_then(|m| m.export)
+        .unwrap_or_default()
+}
+
+/// Collects the target triples requested on the cargo command line via
+/// `--target <triple>` or `--target=<triple>` (both repeatable).
+fn target_triples(cargo_args: &[&str]) -> Vec<String> {
+    let mut triples = Vec::new();
+    let mut iter = cargo_args.iter();
+    while let Some(arg) = iter.next() {
+        if let Some(rest) = arg.strip_prefix("--target=") {
+            triples.push(rest.to_string());
+        } else if *arg == "--target" {
+            if let Some(val) = iter.next() {
+                triples.push(val.to_string());
+            }
         }
     }
+    triples
+}
+
+/// Finds which requested triple an artifact belongs to by matching a
+/// `target/<triple>/` path component. Returns `None` for host builds (no
+/// `--target`), where cargo omits the triple segment entirely.
+fn triple_for(path: &Path, triples: &[String]) -> Option<String> {
+    let components = path
+        .components()
+        .filter_map(|c| c.as_os_str().to_str())
+        .collect::<Vec<_>>();
+    triples
+        .iter()
+        .find(|triple| components.contains(&triple.as_str()))
+        .cloned()
+}
+
+/// Infers the cargo profile directory (`debug`, `release`, or a custom
+/// profile name) an artifact was built under from its path, looking through a
+/// trailing `deps/` directory.
+fn profile_for(path: &Path) -> Option<String> {
+    let mut dir = path.parent()?;
+    if dir.file_name().and_then(|n| n.to_str()) == Some("deps") {
+        dir = dir.parent()?;
+    }
+    dir.file_name()
+        .and_then(|n| n.to_str())
+        .map(|s| s.to_string())
+}
+
+/// Expands the `{pkg}`, `{target}`, `{triple}`, `{profile}`, and `{date}`
+/// placeholders in a tag template using the metadata of a single artifact.
+/// Placeholders without a value (e.g. `{triple}` on a host build) expand to the
+/// empty string.
+fn expand_tag(template: &str, artifact: &PendingArtifact) -> String {
+    template
+        .replace("{pkg}", &artifact.package)
+        .replace("{target}", &artifact.target)
+        .replace("{triple}", artifact.triple.as_deref().unwrap_or(""))
+        .replace("{profile}", artifact.profile.as_deref().unwrap_or(""))
+        .replace("{date}", &today())
+}
+
+/// The current local date formatted as `YYYYMMDD` for the `{date}` placeholder.
+fn today() -> String {
+    chrono::Local::now().format("%Y%m%d").to_string()
+}
+
+/// The kind label recorded for an executable target. Cargo lists a single
+/// crate kind for bin/test/bench/example targets; a library crate's own
+/// unit-test binary carries kind `["lib"]`. We report the first kind, falling
+/// back to `"bin"` for the (practically impossible) empty case.
+fn exe_kind(kinds: &[String]) -> String {
+    kinds.first().cloned().unwrap_or_else(|| "bin".to_string())
+}
+
+/// Whether a crate kind names a loadable/linkable library we export from the
+/// `filenames` list. The compiler-only kinds (`lib`/`rlib`/`rmeta`,
+/// `proc-macro`) and build scripts (`custom-build`) are skipped so we don't
+/// flood the output directory with every dependency's intermediate files.
+fn is_library_export_kind(kind: &str) -> bool {
+    matches!(kind, "cdylib" | "staticlib" | "dylib")
+}
+
+/// Whether `path`'s extension is the one cargo emits for the given library
+/// crate kind. Used to pick the right file out of a multi-output target's
+/// `filenames` (which also lists `.rlib`/`.rmeta`).
+fn extension_matches_kind(path: &Path, kind: &str) -> bool {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    match kind {
+        "staticlib" => matches!(ext, "a" | "lib"),
+        "cdylib" | "dylib" => matches!(ext, "so" | "dylib" | "dll"),
+        _ => false,
+    }
+}
+
+/// Selects a crate kind against the resolved `--kind`/manifest filter. With no
+/// filter configured every candidate is kept.
+fn kind_selected(filter: &Option<Vec<String>>, kind: &str) -> bool {
+    match filter {
+        Some(kinds) => kinds.iter().any(|k| k == kind),
+        None => true,
+    }
 }
 
 fn build_opts() -> Options {
@@ -131,9 +470,26 @@ fn build_opts() -> Options {
     opts.optopt(
         "t",
         "tag",
-        "tag name to add to the resulting binaries file names",
+        "tag to add to the resulting file names; supports {pkg} {target} {triple} {profile} {date}",
         "TAG",
     );
+    opts.optmulti(
+        "k",
+        "kind",
+        "only export targets of the given kind (bin, test, bench, …); repeatable",
+        "KIND",
+    );
+    opts.optflag(
+        "s",
+        "split-by-target",
+        "place artifacts into PATH/<triple>/ subdirectories per target triple",
+    );
+    opts.optopt(
+        "i",
+        "index",
+        "write a JSON index of the exported artifacts to the given file",
+        "PATH",
+    );
     opts.optflag(
         "n",
         "no-default-options",
@@ -170,3 +526,133 @@ fn print_usage_and_exit(opts: &Options, fail: Option<Fail>) -> ! {
     let exit_code = if fail.is_some() { 1 } else { 0 };
     exit(exit_code)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pkg(repr: &str) -> PackageId {
+        PackageId {
+            repr: repr.to_string(),
+        }
+    }
+
+    #[test]
+    fn package_name_legacy_format() {
+        // "name version (source)" — the pre-1.77 spelling.
+        assert_eq!(
+            package_name(&pkg("foo 0.1.0 (path+file:///tmp/foo)")),
+            "foo"
+        );
+        assert_eq!(
+            package_name(&pkg(
+                "serde 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)"
+            )),
+            "serde"
+        );
+    }
+
+    #[test]
+    fn package_name_modern_named_format() {
+        // "source#name@version" — registry and git dependencies.
+        assert_eq!(
+            package_name(&pkg(
+                "registry+https://github.com/rust-lang/crates.io-index#serde@1.0.0"
+            )),
+            "serde"
+        );
+    }
+
+    #[test]
+    fn package_name_modern_bare_version_format() {
+        // "source#version" — path/git packages whose name is the last source
+        // path segment.
+        assert_eq!(
+            package_name(&pkg("path+file:///home/user/foo#0.1.0")),
+            "foo"
+        );
+        assert_eq!(
+            package_name(&pkg("git+https://example.com/bar/baz#1.2.3")),
+            "baz"
+        );
+    }
+
+    #[test]
+    fn triple_for_matches_path_segment() {
+        let triples = vec!["x86_64-unknown-linux-gnu".to_string()];
+        let path = Path::new("target/x86_64-unknown-linux-gnu/debug/deps/foo-abc123");
+        assert_eq!(
+            triple_for(path, &triples),
+            Some("x86_64-unknown-linux-gnu".to_string())
+        );
+    }
+
+    #[test]
+    fn triple_for_host_build_is_none() {
+        // A host build has no `target/<triple>/` segment.
+        let triples = vec!["aarch64-apple-darwin".to_string()];
+        let path = Path::new("target/debug/deps/foo-abc123");
+        assert_eq!(triple_for(path, &triples), None);
+    }
+
+    #[test]
+    fn triple_for_ignores_untargeted_triples() {
+        // Only triples actually requested on the command line are recognised.
+        let path = Path::new("target/x86_64-unknown-linux-gnu/release/foo");
+        assert_eq!(triple_for(path, &[]), None);
+    }
+
+    #[test]
+    fn profile_for_looks_through_deps() {
+        // Test/bench binaries land in `<profile>/deps/`; the profile is the
+        // directory above `deps/`.
+        assert_eq!(
+            profile_for(Path::new("target/debug/deps/foo-abc123")),
+            Some("debug".to_string())
+        );
+        assert_eq!(
+            profile_for(Path::new("target/release/deps/foo-abc123")),
+            Some("release".to_string())
+        );
+    }
+
+    #[test]
+    fn profile_for_direct_artifact() {
+        // Bins land directly in `<profile>/`.
+        assert_eq!(
+            profile_for(Path::new("target/release/foo")),
+            Some("release".to_string())
+        );
+    }
+
+    #[test]
+    fn expand_tag_substitutes_placeholders() {
+        let artifact = PendingArtifact {
+            package: "foo".to_string(),
+            target: "foo_bench".to_string(),
+            kind: "bench".to_string(),
+            triple: Some("x86_64-unknown-linux-gnu".to_string()),
+            profile: Some("release".to_string()),
+            from: PathBuf::new(),
+        };
+        assert_eq!(expand_tag("{pkg}-{profile}", &artifact), "foo-release");
+        assert_eq!(
+            expand_tag("{target}-{triple}", &artifact),
+            "foo_bench-x86_64-unknown-linux-gnu"
+        );
+    }
+
+    #[test]
+    fn expand_tag_missing_values_are_empty() {
+        // A host build has no triple; its placeholder expands to nothing.
+        let artifact = PendingArtifact {
+            package: "foo".to_string(),
+            target: "foo".to_string(),
+            kind: "bin".to_string(),
+            triple: None,
+            profile: None,
+            from: PathBuf::new(),
+        };
+        assert_eq!(expand_tag("{triple}{profile}", &artifact), "");
+    }
+}